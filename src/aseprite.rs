@@ -0,0 +1,49 @@
+use crate::convert::{Config, Palette, TileAtlas, TileMap};
+use crate::{evgfx_error, Error};
+use asefile::AsepriteFile;
+use image::{Rgb, RgbaImage};
+use std::path::Path;
+
+impl Config {
+	/// Loads an Aseprite document and converts its frames through the same tile/palette
+	/// pipeline as `convert_image`, flattening each frame's visible layers and sharing one
+	/// `Palette` and `TileAtlas` across every returned frame. If `tag` is given, only that
+	/// frame tag's range is converted, and a name the file has no tag for is an error; if
+	/// `tag` is `None`, every frame is.
+	pub fn convert_aseprite(&self, ase_path: &str, tag: Option<&str>) -> Result<(Palette, TileAtlas, Vec<TileMap>), Error> {
+		let ase = AsepriteFile::read_file(Path::new(ase_path)).map_err(|err| {
+			format!("Failed to open {ase_path}: {err}")
+		})?;
+
+		let (from_frame, to_frame) = match tag {
+			Some(name) => {
+				let tag = ase.tags().find(|tag| tag.name() == name).ok_or_else(|| {
+					evgfx_error!("Aseprite file {ase_path} has no tag named {name}")
+				})?;
+				(tag.from_frame(), tag.to_frame())
+			}
+			None => (0, ase.num_frames() - 1),
+		};
+
+		// Honor the file's own declared transparent color (indexed-mode sprites only) if
+		// the caller hasn't already set one.
+		let mut config = self.clone();
+		if config.transparency_color.is_none() {
+			if let Some(color) = ase.transparent_color_index().and_then(|index| {
+				ase.palette().and_then(|palette| palette.color(index as u32))
+			}) {
+				config.transparency_color = Some(Rgb([color.red(), color.green(), color.blue()]));
+			}
+		}
+
+		let mut palette = Palette::new();
+		let mut tiles = TileAtlas::new();
+		let mut tilemaps = Vec::new();
+		for frame in from_frame..=to_frame {
+			let image: RgbaImage = ase.frame(frame).image();
+			tilemaps.push(config.convert_rgba(&image, &mut palette, &mut tiles)?);
+		}
+
+		Ok((palette, tiles, tilemaps))
+	}
+}
@@ -10,12 +10,14 @@ use std::io::Write;
 #[derive(PartialEq)]
 pub struct Tile {
 	indexes: Vec<usize>,
+	width: usize,
 }
 
 impl Tile {
-	pub fn new() -> Self {
+	pub fn new(width: usize) -> Self {
 		Self {
-			indexes: Vec::<usize>::new()
+			indexes: Vec::<usize>::new(),
+			width,
 		}
 	}
 
@@ -30,29 +32,146 @@ impl Tile {
 		}
 		Ok(result)
 	}
+
+	pub fn convert_to_8bpp(&self) -> Result<Vec<u8>, String> {
+		let mut result = Vec::<u8>::with_capacity(self.indexes.len());
+		for &index in &self.indexes {
+			// Limit the number of valid indices to 256.
+			if index >= 256 {
+				return Err(String::from("Input image has too many colors"));
+			}
+			result.push(index as u8);
+		}
+		Ok(result)
+	}
+
+	/// Indices with each row (of `width` pixels) reversed, as if mirrored horizontally.
+	fn flipped_horizontal(&self) -> Vec<usize> {
+		let mut result = Vec::with_capacity(self.indexes.len());
+		for row in self.indexes.chunks(self.width) {
+			result.extend(row.iter().rev());
+		}
+		result
+	}
+
+	/// Indices with row order reversed, as if mirrored vertically.
+	fn flipped_vertical(&self) -> Vec<usize> {
+		let mut result = Vec::with_capacity(self.indexes.len());
+		for row in self.indexes.chunks(self.width).rev() {
+			result.extend(row);
+		}
+		result
+	}
+
+	/// Indices mirrored both horizontally and vertically.
+	fn flipped_both(&self) -> Vec<usize> {
+		let mut result = Vec::with_capacity(self.indexes.len());
+		for row in self.indexes.chunks(self.width).rev() {
+			result.extend(row.iter().rev());
+		}
+		result
+	}
+}
+
+/// Horizontal/vertical mirroring needed to match a candidate tile against an atlas entry.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Flip {
+	None,
+	Horizontal,
+	Vertical,
+	Both,
+}
+
+impl Flip {
+	fn horizontal(self) -> bool {
+		matches!(self, Flip::Horizontal | Flip::Both)
+	}
+
+	fn vertical(self) -> bool {
+		matches!(self, Flip::Vertical | Flip::Both)
+	}
 }
 
 pub struct TileAtlas {
-	atlas: Vec<Tile>
+	atlas: Vec<Tile>,
+	/// Number of tiles at the start of `atlas` that were seeded from a preloaded tileset
+	/// rather than found in a converted image. `write_4bpp`/`write_8bpp` skip these, since
+	/// they already exist in the bank the caller preloaded from.
+	preloaded: usize,
 }
 
 impl TileAtlas {
 	pub fn new() -> Self {
 		Self {
 			atlas: Vec::<Tile>::new(),
+			preloaded: 0,
 		}
 	}
 
-	/// Attempts to add a tile to an atlas.
-	/// If the tile already exists, returns the index of the existing tile.
-	pub fn update(&mut self, new_tile: Tile) -> usize {
+	/// Seeds an atlas from a previously written 4bpp tileset, so `update`/`update_strict`
+	/// dedup new tiles against it and reuse its indices. `sub_width`/`sub_height` must match
+	/// the tile size the bank was originally written with.
+	pub fn from_4bpp(data: &[u8], sub_width: u32, sub_height: u32) -> Result<Self, Error> {
+		let pixels = (sub_width * sub_height) as usize;
+		if pixels % 2 != 0 {
+			return Err(evgfx_error!("4bpp tiles must have an even pixel count"));
+		}
+
+		let bytes_per_tile = pixels / 2;
+		if bytes_per_tile == 0 || data.len() % bytes_per_tile != 0 {
+			return Err(evgfx_error!("Tileset length is not a multiple of the tile size"));
+		}
+
+		let mut atlas = Vec::new();
+		for chunk in data.chunks(bytes_per_tile) {
+			let mut tile = Tile::new(sub_width as usize);
+			for byte in chunk {
+				tile.indexes.push((byte & 0xF) as usize);
+				tile.indexes.push((byte >> 4) as usize);
+			}
+			atlas.push(tile);
+		}
+
+		let preloaded = atlas.len();
+		Ok(Self { atlas, preloaded })
+	}
+
+	/// Finds an existing tile matching `new_tile`, directly or under some flip, returning
+	/// its index and the flip needed to reproduce `new_tile` from it.
+	fn find(&self, new_tile: &Tile) -> Option<(usize, Flip)> {
 		for (i, tile) in self.atlas.iter().enumerate() {
-			if *tile == new_tile {
-				return i;
+			if *tile == *new_tile {
+				return Some((i, Flip::None));
+			}
+			if tile.indexes == new_tile.flipped_horizontal() {
+				return Some((i, Flip::Horizontal));
+			}
+			if tile.indexes == new_tile.flipped_vertical() {
+				return Some((i, Flip::Vertical));
+			}
+			if tile.indexes == new_tile.flipped_both() {
+				return Some((i, Flip::Both));
 			}
 		}
+		None
+	}
+
+	/// Attempts to add a tile to an atlas.
+	/// If a matching tile already exists (directly or as a horizontal/vertical/both flip),
+	/// returns its index and the flip needed instead of storing a duplicate.
+	pub fn update(&mut self, new_tile: Tile) -> (usize, Flip) {
+		if let Some(found) = self.find(&new_tile) {
+			return found;
+		}
 		self.atlas.push(new_tile);
-		self.atlas.len() - 1
+		(self.atlas.len() - 1, Flip::None)
+	}
+
+	/// Like `update`, but requires `new_tile` to already be present in the atlas (typically
+	/// a preloaded, fixed tileset such as a font). Returns an error instead of appending new
+	/// tiles, since every tile is expected to come from the known set.
+	pub fn update_strict(&mut self, new_tile: Tile) -> Result<(usize, Flip), Error> {
+		self.find(&new_tile).ok_or_else(|| evgfx_error!("Tile not found in the preloaded tileset"))
 	}
 
 	pub fn write_4bpp(&self, output_path: &str) -> Result<(), Error> {
@@ -60,15 +179,35 @@ impl TileAtlas {
 			format!("Failed to create: {output_path}: {err}")
 		})?;
 
-		for i in &self.atlas {
-			output.write(&i.convert_to_4bpp()?)?;
+		for i in &self.atlas[self.preloaded..] {
+			output.write_all(&i.convert_to_4bpp()?)?;
+		}
+		Ok(())
+	}
+
+	pub fn write_8bpp(&self, output_path: &str) -> Result<(), Error> {
+		let mut output = File::create(output_path).map_err(|err| {
+			format!("Failed to create: {output_path}: {err}")
+		})?;
+
+		for i in &self.atlas[self.preloaded..] {
+			output.write_all(&i.convert_to_8bpp()?)?;
 		}
 		Ok(())
 	}
 }
 
+/// A single entry in a tile map: which tile to draw, how it should be mirrored, and (for
+/// multi-palette images) which hardware palette bank its colors come from.
+#[derive(Clone, Copy)]
+pub struct MapEntry {
+	pub tile_index: usize,
+	pub flip: Flip,
+	pub palette_bank: usize,
+}
+
 pub struct TileMap {
-	map: Vec<Vec<usize>>,
+	map: Vec<Vec<MapEntry>>,
 }
 
 impl TileMap {
@@ -84,11 +223,42 @@ impl TileMap {
 		})?;
 
 		for i in &self.map {
-			for i in i {
-				if *i >= u8::MAX as usize {
-					return Err(evgfx_error!("Too many tiles: index {i} is too large for an 8-bit map"));
+			for entry in i {
+				if entry.tile_index >= u8::MAX as usize {
+					return Err(evgfx_error!("Too many tiles: index {} is too large for an 8-bit map", entry.tile_index));
+				}
+				output.write_all(&[entry.tile_index as u8])?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Emits the hardware background map-entry layout as little-endian u16s: tile index in
+	/// the low 10 bits, horizontal flip at bit 10, vertical flip at bit 11, and the 4-bit
+	/// palette bank in bits 12-15. Unlike `write_8bit`, this has no 256-tile ceiling.
+	pub fn write_16bit(&self, output_path: &str) -> Result<(), Error> {
+		let mut output = File::create(output_path).map_err(|err| {
+			format!("Failed to create: {output_path}: {err}")
+		})?;
+
+		for i in &self.map {
+			for entry in i {
+				if entry.tile_index >= 1 << 10 {
+					return Err(evgfx_error!("Too many tiles: index {} is too large for a 16-bit map entry", entry.tile_index));
 				}
-				output.write(&[*i as u8])?;
+				if entry.palette_bank >= 16 {
+					return Err(evgfx_error!("Palette bank {} does not fit in the 4-bit map-entry field", entry.palette_bank));
+				}
+
+				let mut value = entry.tile_index as u16;
+				if entry.flip.horizontal() {
+					value |= 1 << 10;
+				}
+				if entry.flip.vertical() {
+					value |= 1 << 11;
+				}
+				value |= (entry.palette_bank as u16) << 12;
+				output.write_all(&value.to_le_bytes())?;
 			}
 		}
 		Ok(())
@@ -104,10 +274,24 @@ impl Palette {
 		Self { table: Vec::<Rgb<u8>>::new() }
 	}
 
+	/// Seeds a palette from an existing, ordered list of colors (e.g. the exact colors a
+	/// preloaded `TileAtlas::from_4bpp` tileset was originally indexed against), so new
+	/// images sharing that tileset assign the same indices to the same colors instead of
+	/// rebuilding the palette from scratch in whatever order they first encounter colors. If
+	/// the caller also uses `with_transparency_color`, that color must be `colors[0]`, since
+	/// `convert_rgba` only ever reserves index 0 for it.
+	pub fn preloaded(colors: Vec<Rgb<u8>>) -> Self {
+		Self { table: colors }
+	}
+
 	pub fn insert(&mut self, color: &Rgb<u8>) {
 		self.table.push(*color);
 	}
 
+	pub fn is_empty(&self) -> bool {
+		self.table.is_empty()
+	}
+
 	pub fn get(&mut self, color: &Rgba<u8>) -> Option<usize> {
 		for (i, c) in self.table.iter().enumerate() {
 			if *c == color.to_rgb() {
@@ -117,6 +301,28 @@ impl Palette {
 		None
 	}
 
+	/// Finds the index of the closest palette entry to `color` by squared Euclidean RGB
+	/// distance. Used once a fixed (e.g. quantized) palette can no longer grow. If
+	/// `skip_first` is set, index 0 is excluded from the search, since it's reserved for the
+	/// transparency color and should never be chosen for an opaque pixel; if index 0 is the
+	/// palette's only entry, it is still returned.
+	pub fn nearest(&self, color: &Rgba<u8>, skip_first: bool) -> usize {
+		let target = color.to_rgb();
+		let start = if skip_first && self.table.len() > 1 { 1 } else { 0 };
+		let mut best = start;
+		let mut best_dist = u32::MAX;
+		for (i, c) in self.table.iter().enumerate().skip(start) {
+			let dist = channel_dist(c.0[0], target.0[0])
+				+ channel_dist(c.0[1], target.0[1])
+				+ channel_dist(c.0[2], target.0[2]);
+			if dist < best_dist {
+				best_dist = dist;
+				best = i;
+			}
+		}
+		best
+	}
+
 	pub fn write_rgb555(&self, output_path: &str, skip_first: bool) -> Result<(), Error> {
 		let mut output = File::create(output_path).map_err(|err| {
 			format!("Failed to create: {output_path}: {err}")
@@ -128,21 +334,83 @@ impl Palette {
 			&self.table
 		};
 
-		for i in table {
-			output.write(
-				&(
-					(i.0[0] as u16) >> 3
-					| ((i.0[1] as u16) >> 3) << 5
-					| ((i.0[2] as u16) >> 3) << 10
-				).to_le_bytes()
-			)?;
+		for color in table {
+			output.write_all(&rgb555_bytes(color))?;
+		}
+		Ok(())
+	}
+
+	/// Errors if the palette has grown past the capacity of `bit_depth` (16 entries for
+	/// `Bpp4`, 256 for `Bpp8`), so an image with too many colors for the configured
+	/// `Config::bit_depth` fails at conversion rather than later when it's written. It does
+	/// not check which writer the caller then uses.
+	pub fn check_bit_depth(&self, bit_depth: BitDepth) -> Result<(), Error> {
+		let capacity = match bit_depth {
+			BitDepth::Bpp4 => 16,
+			BitDepth::Bpp8 => 256,
+		};
+		if self.table.len() > capacity {
+			return Err(evgfx_error!("Palette has {} colors, which is too many for {}bpp output (max {capacity})", self.table.len(), match bit_depth { BitDepth::Bpp4 => 4, BitDepth::Bpp8 => 8 }));
+		}
+		Ok(())
+	}
+}
+
+/// Up to 16 separate hardware-sized (16-color) palettes, used when an image's total color
+/// count exceeds a single palette but each individual tile's colors still fit within 16.
+pub struct PaletteBank {
+	banks: Vec<Palette>,
+}
+
+impl PaletteBank {
+	pub fn new() -> Self {
+		Self { banks: Vec::new() }
+	}
+
+	fn push(&mut self, palette: Palette) {
+		self.banks.push(palette);
+	}
+
+	/// Writes every bank concatenated, each padded out to a full 16-color (or 15, if
+	/// `skip_first` reserves slot 0) hardware palette so bank boundaries stay aligned.
+	pub fn write_rgb555(&self, output_path: &str, skip_first: bool) -> Result<(), Error> {
+		let mut output = File::create(output_path).map_err(|err| {
+			format!("Failed to create: {output_path}: {err}")
+		})?;
+
+		let skip = skip_first as usize;
+		for bank in &self.banks {
+			let table = &bank.table[skip.min(bank.table.len())..];
+			for color in table {
+				output.write_all(&rgb555_bytes(color))?;
+			}
+			for _ in table.len()..(16 - skip) {
+				output.write_all(&[0, 0])?;
+			}
 		}
 		Ok(())
 	}
 }
 
+/// Packs an RGB555 color into little-endian bytes.
+fn rgb555_bytes(color: &Rgb<u8>) -> [u8; 2] {
+	(
+		(color.0[0] as u16) >> 3
+		| ((color.0[1] as u16) >> 3) << 5
+		| ((color.0[2] as u16) >> 3) << 10
+	).to_le_bytes()
+}
+
+/// Output pixel bit-depth for a tileset.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BitDepth {
+	Bpp4,
+	Bpp8,
+}
+
 /// Configuration options for splicing images.
 /// A single config can be used for multiple images.
+#[derive(Clone)]
 pub struct Config {
 	/// How large a metatile/sprite is within the input map.
 	// For animation spritesheets this could potentially change.
@@ -160,6 +428,19 @@ pub struct Config {
 	pub transparency_color: Option<Rgb<u8>>,
 	/// If the alpha channel is lower than this value, the color is transparent.
 	pub alpha_threshold: u8,
+	/// If set, the image's opaque colors are reduced to at most this many palette entries
+	/// via median-cut quantization before tiles are indexed.
+	pub quantization: Option<usize>,
+	/// If set, diffuses quantization error across the image (Floyd-Steinberg) before tiles
+	/// are indexed, so gradients don't band on a reduced palette.
+	pub dithering: bool,
+	/// Output pixel bit-depth, selecting whether `TileAtlas` should be written with
+	/// `write_4bpp` (16-color) or `write_8bpp` (256-color).
+	pub bit_depth: BitDepth,
+	/// If set, tiles must already exist in the `TileAtlas` passed to `convert_image_with_atlas`
+	/// (e.g. a preloaded font or fixed tileset); any tile not found there is an error instead
+	/// of being appended.
+	pub strict_tileset: bool,
 }
 
 impl Config {
@@ -171,6 +452,10 @@ impl Config {
 			sub_height: 8,
 			transparency_color: None,
 			alpha_threshold: 128, // half seems good???
+			quantization: None,
+			dithering: false,
+			bit_depth: BitDepth::Bpp4,
+			strict_tileset: false,
 		}
 	}
 
@@ -188,20 +473,92 @@ impl Config {
 		self
 	}
 
+	/// Reduce the image's opaque colors down to at most `max_colors` via median-cut
+	/// quantization before tiles are indexed, so photographic or rich-color source art
+	/// can still be packed into a 16-color hardware palette.
+	pub fn with_quantization(mut self, max_colors: usize) -> Self {
+		self.quantization = Some(max_colors);
+		self
+	}
+
+	/// Diffuse quantization error across the image (Floyd-Steinberg) before tiles are
+	/// indexed, so gradients don't band when reduced to a 16-color palette.
+	pub fn with_dithering(mut self, dithering: bool) -> Self {
+		self.dithering = dithering;
+		self
+	}
+
+	/// Select the output pixel bit-depth, i.e. whether tiles should be written with
+	/// `TileAtlas::write_4bpp` or `TileAtlas::write_8bpp`.
+	pub fn with_bit_depth(mut self, bit_depth: BitDepth) -> Self {
+		self.bit_depth = bit_depth;
+		self
+	}
+
+	/// Require every tile to already exist in the `TileAtlas` passed to
+	/// `convert_image_with_atlas`, erroring on any tile not found there instead of appending
+	/// it. Useful for fonts or fixed tilesets where every glyph must come from a known set.
+	pub fn with_strict_tileset(mut self, strict: bool) -> Self {
+		self.strict_tileset = strict;
+		self
+	}
+
 	/// Convert an image into a list of palettes and indices.
 	/// The resulting `Tile`s may be converted into a particular format.
 	pub fn convert_image(&self, img_path: &str) -> Result<(Palette, TileAtlas, TileMap), Error> {
-		let img = &image::open(img_path).map_err(|err| {
+		let img = image::open(img_path).map_err(|err| {
 			format!("Failed to open {img_path}: {err}")
-		})?;
+		})?.to_rgba8();
 
-		let mut tilemap = TileMap::new();
 		let mut tiles = TileAtlas::new();
 		let mut palette = Palette::new();
+		let tilemap = self.convert_rgba(&img, &mut palette, &mut tiles)?;
+		Ok((palette, tiles, tilemap))
+	}
+
+	/// Converts an already-decoded RGBA image into a tile map, inserting new colors into
+	/// `palette` and new tiles into `tiles` (reusing existing ones via dedup/flip-matching).
+	/// Used by `convert_image` and by multi-frame/multi-image sources (e.g. Aseprite
+	/// documents, or a preloaded shared tile bank) that want one `Palette`/`TileAtlas` shared
+	/// across several calls.
+	pub(crate) fn convert_rgba(
+		&self,
+		img: &image::RgbaImage,
+		palette: &mut Palette,
+		tiles: &mut TileAtlas,
+	) -> Result<TileMap, Error> {
+		if self.dithering && self.quantization.is_none() {
+			return Err(evgfx_error!("with_dithering requires with_quantization: dithering needs a fixed palette to diffuse error against"));
+		}
+
+		let mut img = img.clone();
+		let reserve_first = self.transparency_color.is_some();
 		if let Some(transparency_color) = self.transparency_color {
-			palette.insert(&transparency_color);
+			match palette.get(&transparency_color.to_rgba()) {
+				Some(0) => {}
+				Some(_) => return Err(evgfx_error!("transparency_color must be at palette index 0, but it's already present elsewhere in the supplied palette")),
+				None if palette.is_empty() => palette.insert(&transparency_color),
+				None => return Err(evgfx_error!("transparency_color must already be the first entry of a preloaded, non-empty palette")),
+			}
+		}
+
+		if let Some(max_colors) = self.quantization {
+			// Reserve a slot for the transparency color so the quantized palette still fits
+			// within max_colors once it's inserted.
+			let max_colors = if reserve_first { max_colors.saturating_sub(1) } else { max_colors };
+			let view = *img.view(0, 0, img.width(), img.height());
+			for color in quantize_image(view, max_colors, self.alpha_threshold) {
+				if palette.get(&color.to_rgba()).is_none() {
+					palette.insert(&color);
+				}
+			}
+		}
+
+		if self.dithering {
+			dither_image(&mut img, palette, self.alpha_threshold, reserve_first);
 		}
 
+		let mut tilemap = TileMap::new();
 		for tile_y in (0..img.height()).step_by(self.height as usize) {
 			for tile_x in (0..img.width()).step_by(self.width as usize) {
 				for subtile_y in (tile_y..(tile_y + self.height)).step_by(self.sub_height as usize) {
@@ -214,27 +571,202 @@ impl Config {
 								self.sub_width,
 								self.sub_height,
 							),
-							&mut palette,
+							palette,
 							self.alpha_threshold,
+							self.quantization.is_some(),
+							reserve_first,
 						);
-						let index = tiles.update(tile);
+						let (tile_index, flip) = if self.strict_tileset {
+							tiles.update_strict(tile)?
+						} else {
+							tiles.update(tile)
+						};
 						let last_row = tilemap.map.len() - 1;
-						tilemap.map[last_row].push(index);
+						tilemap.map[last_row].push(MapEntry { tile_index, flip, palette_bank: 0 });
 					}
 				}
 			}
 		}
-		Ok((palette, tiles, tilemap))
+		palette.check_bit_depth(self.bit_depth)?;
+		Ok(tilemap)
+	}
+
+	/// Convert an image into a tile map using a `TileAtlas` the caller preloaded (e.g. via
+	/// `TileAtlas::from_4bpp`), so new unique tiles get indices after the preloaded ones and
+	/// map indices stay stable across multiple images that share graphics. `palette` must
+	/// already contain the exact, ordered colors the preloaded tileset was indexed against
+	/// (e.g. via `Palette::preloaded`, or the same `Palette` instance reused across calls in
+	/// one run) — `TileAtlas::update`/`update_strict` dedup tiles by comparing raw palette
+	/// indices, so a `palette` that assigns colors to different indices than the preloaded
+	/// tileset did will fail to match tiles that are otherwise identical.
+	pub fn convert_image_with_atlas(&self, img_path: &str, palette: &mut Palette, tiles: &mut TileAtlas) -> Result<TileMap, Error> {
+		let img = image::open(img_path).map_err(|err| {
+			format!("Failed to open {img_path}: {err}")
+		})?.to_rgba8();
+
+		self.convert_rgba(&img, palette, tiles)
+	}
+
+	/// Convert an image into a set of 16-color hardware palette banks, a deduplicated tile
+	/// atlas, and a tile map, instead of one global palette. Each subtile's distinct colors
+	/// are packed into as few banks as possible so the whole image's color count can exceed
+	/// 16 as long as no single tile's own colors do.
+	pub fn convert_image_banked(&self, img_path: &str) -> Result<(PaletteBank, TileAtlas, TileMap), Error> {
+		let img = &image::open(img_path).map_err(|err| {
+			format!("Failed to open {img_path}: {err}")
+		})?;
+
+		let mut tile_colors = Vec::new();
+		for tile_y in (0..img.height()).step_by(self.height as usize) {
+			for tile_x in (0..img.width()).step_by(self.width as usize) {
+				for subtile_y in (tile_y..(tile_y + self.height)).step_by(self.sub_height as usize) {
+					for subtile_x in (tile_x..(tile_x + self.width)).step_by(self.sub_width as usize) {
+						tile_colors.push(subtile_colors(
+							*img.view(subtile_x, subtile_y, self.sub_width, self.sub_height),
+							self.alpha_threshold,
+						));
+					}
+				}
+			}
+		}
+
+		let reserved = self.transparency_color.is_some() as usize;
+		let assignment = pack_palette_banks(&tile_colors, 16 - reserved)?;
+
+		let mut banks = PaletteBank::new();
+		for colors in &assignment.banks {
+			let mut palette = Palette::new();
+			if let Some(transparency_color) = self.transparency_color {
+				palette.insert(&transparency_color);
+			}
+			for color in colors {
+				palette.insert(color);
+			}
+			banks.push(palette);
+		}
+
+		let mut tilemap = TileMap::new();
+		let mut tiles = TileAtlas::new();
+		let mut subtile_index = 0;
+		for tile_y in (0..img.height()).step_by(self.height as usize) {
+			for tile_x in (0..img.width()).step_by(self.width as usize) {
+				for subtile_y in (tile_y..(tile_y + self.height)).step_by(self.sub_height as usize) {
+					tilemap.map.push(Vec::new());
+					for subtile_x in (tile_x..(tile_x + self.width)).step_by(self.sub_width as usize) {
+						let bank_index = assignment.tile_banks[subtile_index];
+						let tile = create_tile(
+							*img.view(
+								subtile_x,
+								subtile_y,
+								self.sub_width,
+								self.sub_height,
+							),
+							&mut banks.banks[bank_index],
+							self.alpha_threshold,
+							false,
+							self.transparency_color.is_some(),
+						);
+						let (tile_index, flip) = tiles.update(tile);
+						let last_row = tilemap.map.len() - 1;
+						tilemap.map[last_row].push(MapEntry { tile_index, flip, palette_bank: bank_index });
+						subtile_index += 1;
+					}
+				}
+			}
+		}
+		Ok((banks, tiles, tilemap))
+	}
+}
+
+/// Returns the distinct opaque colors used within a single subtile.
+fn subtile_colors<T: GenericImageView<Pixel = Rgba<u8>>>(img: T, alpha_threshold: u8) -> Vec<Rgb<u8>> {
+	let mut colors = Vec::new();
+	for y in 0..img.height() {
+		for x in 0..img.width() {
+			let pixel = img.get_pixel(x, y);
+			if pixel.0[3] < alpha_threshold {
+				continue;
+			}
+			let color = pixel.to_rgb();
+			if !colors.contains(&color) {
+				colors.push(color);
+			}
+		}
+	}
+	colors
+}
+
+/// The result of packing each tile's color set into hardware palette banks.
+struct BankAssignment {
+	/// Each bank's set of colors, in the order they should be inserted into a `Palette`.
+	banks: Vec<Vec<Rgb<u8>>>,
+	/// For each tile, in the same order as the input, which bank index it was assigned to.
+	tile_banks: Vec<usize>,
+}
+
+/// Packs each tile's color set into as few `capacity`-color banks as possible using greedy
+/// first-fit-decreasing: tiles are considered largest-color-set first, and each is placed
+/// in the first bank whose union with the tile's colors still fits within `capacity`,
+/// opening a new bank (up to 16 total) when none fits.
+fn pack_palette_banks(tile_colors: &[Vec<Rgb<u8>>], capacity: usize) -> Result<BankAssignment, Error> {
+	let mut order: Vec<usize> = (0..tile_colors.len()).collect();
+	order.sort_by_key(|&i| std::cmp::Reverse(tile_colors[i].len()));
+
+	let mut banks: Vec<Vec<Rgb<u8>>> = Vec::new();
+	let mut tile_banks = vec![0; tile_colors.len()];
+
+	for i in order {
+		let colors = &tile_colors[i];
+		if colors.len() > capacity {
+			return Err(evgfx_error!("Tile uses {} colors, which exceeds the {capacity}-color bank capacity", colors.len()));
+		}
+
+		let bank_index = banks.iter()
+			.position(|bank| bank_union_len(bank, colors) <= capacity)
+			.unwrap_or(banks.len());
+
+		if bank_index == banks.len() {
+			if banks.len() >= 16 {
+				return Err(evgfx_error!("Image requires more than 16 palette banks"));
+			}
+			banks.push(Vec::new());
+		}
+
+		for color in colors {
+			if !banks[bank_index].contains(color) {
+				banks[bank_index].push(*color);
+			}
+		}
+		tile_banks[i] = bank_index;
+	}
+
+	Ok(BankAssignment { banks, tile_banks })
+}
+
+/// The number of distinct colors in `bank` after adding any of `colors` it doesn't already have.
+fn bank_union_len(bank: &[Rgb<u8>], colors: &[Rgb<u8>]) -> usize {
+	let mut len = bank.len();
+	for color in colors {
+		if !bank.contains(color) {
+			len += 1;
+		}
 	}
+	len
 }
 
 /// Convert an image into a list of palette indices.
+/// If `fixed_palette` is set (e.g. after quantization), colors missing from `palette` are
+/// mapped to their nearest existing entry instead of being inserted. If `reserve_first` is
+/// set, that nearest-entry search excludes index 0, since it's reserved for the transparency
+/// color and must not be chosen for an opaque pixel.
 fn create_tile<T: GenericImageView<Pixel = Rgba<u8>>>(
 	img: T,
 	palette: &mut Palette,
 	alpha_threshold: u8,
+	fixed_palette: bool,
+	reserve_first: bool,
 ) -> Tile {
-	let mut tile = Tile::new();
+	let mut tile = Tile::new(img.width() as usize);
 	for y in 0..img.height() {
 		for x in 0..img.width() {
 			let pixel = img.get_pixel(x, y);
@@ -243,13 +775,160 @@ fn create_tile<T: GenericImageView<Pixel = Rgba<u8>>>(
 				continue;
 			}
 
-			if palette.get(&pixel).is_none() {
-				palette.insert(&pixel.to_rgb());
-			}
-			// Because we explicitly add missing colors above,
-			// this is safe to unwrap.
-			tile.indexes.push(palette.get(&pixel).unwrap());
+			let index = match palette.get(&pixel) {
+				Some(index) => index,
+				None if fixed_palette => palette.nearest(&pixel, reserve_first),
+				None => {
+					palette.insert(&pixel.to_rgb());
+					// Because we explicitly just inserted this color, this is safe to unwrap.
+					palette.get(&pixel).unwrap()
+				}
+			};
+			tile.indexes.push(index);
 		}
 	}
 	tile
 }
+
+/// Reduces an image's opaque colors to at most `max_colors` representative colors using
+/// median-cut: start with one box spanning every opaque pixel, repeatedly split the box
+/// whose longest RGB channel range is largest at the median of that channel, and stop once
+/// `max_colors` boxes exist. Each box's representative color is the average of its pixels.
+fn quantize_image<T: GenericImageView<Pixel = Rgba<u8>>>(
+	img: T,
+	max_colors: usize,
+	alpha_threshold: u8,
+) -> Vec<Rgb<u8>> {
+	let mut colors = Vec::new();
+	for y in 0..img.height() {
+		for x in 0..img.width() {
+			let pixel = img.get_pixel(x, y);
+			if pixel.0[3] >= alpha_threshold {
+				colors.push(pixel.to_rgb());
+			}
+		}
+	}
+
+	if colors.is_empty() {
+		return Vec::new();
+	}
+
+	let mut boxes = vec![colors];
+	while boxes.len() < max_colors {
+		let split_index = boxes
+			.iter()
+			.enumerate()
+			.max_by_key(|(_, colors)| longest_channel(colors).1)
+			.map(|(i, _)| i)
+			.unwrap();
+
+		if boxes[split_index].len() <= 1 {
+			break;
+		}
+
+		let channel = longest_channel(&boxes[split_index]).0;
+		let mut lower = boxes.swap_remove(split_index);
+		lower.sort_by_key(|color| color.0[channel]);
+		let upper = lower.split_off(lower.len() / 2);
+		boxes.push(lower);
+		boxes.push(upper);
+	}
+
+	// Two boxes can average to the same representative color (e.g. a near-uniform image
+	// keeps splitting symmetric boxes); dedup so callers don't waste palette slots on
+	// duplicate entries.
+	let mut result = Vec::new();
+	for colors in &boxes {
+		let avg = average_color(colors);
+		if !result.contains(&avg) {
+			result.push(avg);
+		}
+	}
+	result
+}
+
+/// Returns the channel (0 = R, 1 = G, 2 = B) with the largest value range among `colors`,
+/// along with that range.
+fn longest_channel(colors: &[Rgb<u8>]) -> (usize, u8) {
+	let mut ranges = [0u8; 3];
+	for (channel, range) in ranges.iter_mut().enumerate() {
+		let min = colors.iter().map(|c| c.0[channel]).min().unwrap();
+		let max = colors.iter().map(|c| c.0[channel]).max().unwrap();
+		*range = max - min;
+	}
+	let channel = (0..3).max_by_key(|&c| ranges[c]).unwrap();
+	(channel, ranges[channel])
+}
+
+/// Averages a box of colors into a single representative color.
+fn average_color(colors: &[Rgb<u8>]) -> Rgb<u8> {
+	let len = colors.len() as u32;
+	let mut sum = [0u32; 3];
+	for color in colors {
+		for (channel, sum) in sum.iter_mut().enumerate() {
+			*sum += color.0[channel] as u32;
+		}
+	}
+	Rgb([(sum[0] / len) as u8, (sum[1] / len) as u8, (sum[2] / len) as u8])
+}
+
+/// Squared distance between two 8-bit channel values.
+fn channel_dist(a: u8, b: u8) -> u32 {
+	let d = a as i32 - b as i32;
+	(d * d) as u32
+}
+
+/// Diffuses quantization error across `img` in place using Floyd-Steinberg: each opaque
+/// pixel is snapped to its nearest `palette` entry, and the per-channel error (original
+/// minus chosen) is spread to not-yet-processed neighbors with weights 7/16 right, 3/16
+/// bottom-left, 5/16 below, and 1/16 bottom-right. Transparent pixels are left untouched
+/// and do not propagate error. Error is accumulated in an f32 buffer over the whole image
+/// (rather than truncated into `img` as it goes) so small weights aren't lost to rounding;
+/// it's only clamped back to u8 range when a pixel is looked up. Must run before tiles are
+/// cut, since error crosses tile boundaries. If `reserve_first` is set, index 0 is excluded
+/// from the nearest-color search, since it's reserved for the transparency color.
+fn dither_image(img: &mut image::RgbaImage, palette: &Palette, alpha_threshold: u8, reserve_first: bool) {
+	let (width, height) = img.dimensions();
+	let mut error_buf = vec![[0f32; 3]; (width * height) as usize];
+	for y in 0..height {
+		for x in 0..width {
+			let pixel = *img.get_pixel(x, y);
+			if pixel.0[3] < alpha_threshold {
+				continue;
+			}
+
+			let error_here = error_buf[(y * width + x) as usize];
+			let adjusted = Rgba([
+				(pixel.0[0] as f32 + error_here[0]).clamp(0.0, 255.0) as u8,
+				(pixel.0[1] as f32 + error_here[1]).clamp(0.0, 255.0) as u8,
+				(pixel.0[2] as f32 + error_here[2]).clamp(0.0, 255.0) as u8,
+				pixel.0[3],
+			]);
+
+			let chosen = palette.table[palette.nearest(&adjusted, reserve_first)];
+			let error = [
+				adjusted.0[0] as f32 - chosen.0[0] as f32,
+				adjusted.0[1] as f32 - chosen.0[1] as f32,
+				adjusted.0[2] as f32 - chosen.0[2] as f32,
+			];
+			img.put_pixel(x, y, Rgba([chosen.0[0], chosen.0[1], chosen.0[2], pixel.0[3]]));
+
+			diffuse_error(&mut error_buf, x as i64 + 1, y as i64, width, height, error, 7.0 / 16.0);
+			diffuse_error(&mut error_buf, x as i64 - 1, y as i64 + 1, width, height, error, 3.0 / 16.0);
+			diffuse_error(&mut error_buf, x as i64, y as i64 + 1, width, height, error, 5.0 / 16.0);
+			diffuse_error(&mut error_buf, x as i64 + 1, y as i64 + 1, width, height, error, 1.0 / 16.0);
+		}
+	}
+}
+
+/// Adds `weight` of `error` to the error buffer at `(x, y)` if in bounds.
+fn diffuse_error(error_buf: &mut [[f32; 3]], x: i64, y: i64, width: u32, height: u32, error: [f32; 3], weight: f32) {
+	if x < 0 || x >= width as i64 || y < 0 || y >= height as i64 {
+		return;
+	}
+
+	let entry = &mut error_buf[(y as u32 * width + x as u32) as usize];
+	for channel in 0..3 {
+		entry[channel] += error[channel] * weight;
+	}
+}
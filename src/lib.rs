@@ -1,3 +1,4 @@
+pub mod aseprite;
 pub mod convert;
 pub extern crate image;
 